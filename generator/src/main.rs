@@ -8,31 +8,72 @@ use markdown::{
     mdast::{ Node, Root, Yaml },
     Constructs, Options, ParseOptions, CompileOptions
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-/// A struct that holds and syncs together the different names that refer to a page 
+/// Site-wide settings loaded once from `site.toml` at the project root.
+/// These replace the directory literals that used to be hardcoded in `main`,
+/// and `base_url`/`title` are made available to every template.
+#[derive(Debug, Deserialize, Serialize)]
+struct Config {
+    base_url: String,
+    title: String,
+    content_dir: String,
+    output_dir: String,
+    templates_dir: String,
+    tag_template: String,
+    tags_index_template: String,
+}
+impl Config {
+    fn load(path: &Path) -> Self {
+        let content = fs::read_to_string(path).unwrap();
+        toml::from_str(&content).unwrap()
+    }
+}
+
+/// Matches a `YYYY-MM-DD-slug` or `YYYY-MM-DD_slug` file stem, splitting out
+/// the date prefix so it doesn't leak into the slug/permalink.
+const DATE_PREFIX_PATTERN: &str =
+    r"^(?P<date>\d{4}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01]))(?:_|-)(?P<slug>.+)$";
+
+/// A struct that holds and syncs together the different names that refer to a page
 /// `src` is the path to a page's source .md file
 /// `out` is the path to the page's compiled .html file
 /// `uri` is the path to access it from a server
+/// `date`/`slug` come from a `YYYY-MM-DD-` filename prefix, if any (front
+/// matter may still override them, see `Page::new`/`with_slug`)
+/// `is_index` marks a page bundle (a directory whose page is `index.md`) —
+/// only these have co-located assets copied, see `copy_page_assets`
 #[derive(Debug)]
 struct PageTriple {
     src: PathBuf,
     out: PathBuf,
     uri: PathBuf,
+    date: Option<String>,
+    slug: Option<String>,
+    base: PathBuf,
+    is_index: bool,
 }
 impl PageTriple {
     fn new(src: PathBuf, src_dir: &Path, out_dir: &Path) -> Self {
-        let file_stem = src.file_stem().unwrap();
-        let is_index = file_stem.to_string_lossy() == "index";
-
-        let irene = { 
-            let base = src.strip_prefix(src_dir).unwrap()
-                .parent().unwrap().to_owned();
-            if is_index {
-                base
-            } else {
-                base.join(file_stem)
-            }
+        let file_stem = src.file_stem().unwrap().to_string_lossy().into_owned();
+        let is_index = file_stem == "index";
+
+        let date_re = regex::Regex::new(DATE_PREFIX_PATTERN).unwrap();
+        let (date, slug, name) = match date_re.captures(&file_stem) {
+            Some(caps) => (
+                Some(caps["date"].to_string()),
+                Some(caps["slug"].to_string()),
+                caps["slug"].to_string(),
+            ),
+            None => (None, None, file_stem),
+        };
+
+        let base = src.strip_prefix(src_dir).unwrap()
+            .parent().unwrap().to_owned();
+        let irene = if is_index {
+            base.clone()
+        } else {
+            base.join(&name)
         };
         let uri = Path::new("/").join(&irene);
         let out = out_dir.join(&irene).join("index.html");
@@ -41,8 +82,25 @@ impl PageTriple {
             src,
             out,
             uri,
+            date,
+            slug,
+            base,
+            is_index,
         }
     }
+
+    /// Recomputes `uri`/`out` from the effective slug (front matter, falling
+    /// back to the one parsed from the filename), so a front-matter override
+    /// actually changes the output path instead of only the template context.
+    /// Index pages don't have a name segment to override.
+    fn with_slug(mut self, slug: Option<&str>, out_dir: &Path) -> Self {
+        if let (false, Some(slug)) = (self.is_index, slug) {
+            let irene = self.base.join(slug);
+            self.uri = Path::new("/").join(&irene);
+            self.out = out_dir.join(&irene).join("index.html");
+        }
+        self
+    }
 }
 
 
@@ -50,6 +108,22 @@ impl PageTriple {
 struct FrontMatter {
     title: String,
     template: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    slug: Option<String>,
+}
+
+/// A single entry in a page's table of contents, derived from a heading in
+/// its mdast. `id` matches the `id` attribute injected into the rendered
+/// heading so in-page anchors resolve.
+#[derive(Debug, Serialize)]
+struct Toc {
+    level: u8,
+    title: String,
+    id: String,
 }
 
 #[derive(Debug)]
@@ -57,11 +131,16 @@ struct Page {
     title: String,
     template: String,
     content: String,
+    toc: Vec<Toc>,
+    tags: Vec<String>,
+    date: Option<String>,
+    slug: Option<String>,
+    word_count: usize,
 }
 impl Page {
     fn new(triple: &PageTriple) -> Self {
 
-        let parseopts = 
+        let parseopts =
         ParseOptions {
             constructs: Constructs {
                 frontmatter: true,
@@ -77,11 +156,14 @@ impl Page {
             ..
         }) = markdown::to_mdast(&content, &parseopts).unwrap() else {
             panic!("cannot parse mdast")
-        }; 
+        };
 
         let FrontMatter {
             title,
             template,
+            tags,
+            date,
+            slug,
         } = children.get(0)
             .map(|node| match node {
                 Node::Yaml(Yaml { value, .. }) => Some(value),
@@ -93,19 +175,71 @@ impl Page {
             .unwrap_or(FrontMatter {
                 title: "NO TITLE".to_string(),
                 template: "base-1.html".to_string(),
+                tags: vec![],
+                date: None,
+                slug: None,
             });
 
+        // front matter takes precedence over what was derived from the filename
+        let date = date.or_else(|| triple.date.clone());
+        let slug = slug.or_else(|| triple.slug.clone());
+
         println!("title: {}, template: {}", title, template);
 
+        // word count over the markdown body's plain text, excluding the
+        // front-matter block (`children[0]` when it's `Node::Yaml`)
+        let word_count = children.iter()
+            .filter(|node| !matches!(node, Node::Yaml(_)))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+            .split_whitespace()
+            .count();
+
+        // de-duplicate ids from headings that slugify to the same text
+        // (e.g. two "Overview" headings) by suffixing repeats with a count
+        let mut id_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+        let toc = children.iter()
+            .filter_map(|node| match node {
+                Node::Heading(heading) => Some(heading),
+                _ => None,
+            })
+            .map(|heading| {
+                let title = heading.children.iter()
+                    .map(ToString::to_string)
+                    .collect::<String>();
+                let base_id = slug::slugify(&title);
+                let count = id_counts.entry(base_id.clone()).or_insert(0);
+                *count += 1;
+                let id = if *count == 1 { base_id } else { format!("{}-{}", base_id, count) };
+                Toc { level: heading.depth, title, id }
+            })
+            .collect();
+
         Self {
             title,
             template,
             content,
+            toc,
+            tags,
+            date,
+            slug,
+            word_count,
         }
     }
 }
 
-fn render(tera: &Tera, page: Page, triple: PageTriple) -> std::io::Result<()> {
+/// The subset of `PageTriple` that templates need, namespaced as `page` in
+/// the Tera `Context` so pages can build absolute links from `config.base_url`.
+#[derive(Debug, Serialize)]
+struct PageContext {
+    uri: String,
+    permalink: String,
+    date: Option<String>,
+    slug: Option<String>,
+}
+
+fn render(tera: &Tera, page: Page, triple: PageTriple, config: &Config) -> std::io::Result<()> {
 
     // markdown-rs options
     let mdopts = Options {
@@ -131,16 +265,51 @@ fn render(tera: &Tera, page: Page, triple: PageTriple) -> std::io::Result<()> {
     println!("-- creating directories...");
     fs::create_dir_all(triple.out.parent().unwrap())?;
 
+    println!("-- copying page assets...");
+    let assets = copy_page_assets(&triple)?;
+
+    const MORE_MARKER: &str = "<!-- more -->";
+    let has_more = page.content.contains(MORE_MARKER);
+    let summary_html = page.content.find(MORE_MARKER)
+        .map(|idx| &page.content[..idx])
+        .map(|excerpt| match markdown::to_html_with_options(excerpt, &mdopts) {
+            Ok(s) => s,
+            Err(s) => s,
+        });
+
     println!("-- rendering markdown...");
     let content_html = match markdown::to_html_with_options(&page.content, &mdopts) {
         Ok(s) => s,
         Err(s) => s,
     };
+    let content_html = inject_heading_ids(&content_html, &page.toc);
 
     println!("-- rendering template...");
+    let uri = triple.uri.to_string_lossy().into_owned();
+    let permalink = format!("{}{}", config.base_url.trim_end_matches('/'), uri);
+
     let mut context = Context::new();
     context.insert("title", &page.title);
     context.insert("content", &content_html);
+    context.insert("config", config);
+    context.insert("page", &PageContext {
+        uri,
+        permalink,
+        date: page.date.clone(),
+        slug: page.slug.clone(),
+    });
+    context.insert("toc", &page.toc);
+
+    let reading_time = (page.word_count as f64 / 200.0).ceil() as u64;
+    context.insert("word_count", &page.word_count);
+    context.insert("reading_time", &reading_time);
+
+    context.insert("has_more", &has_more);
+    if let Some(summary_html) = &summary_html {
+        context.insert("summary", summary_html);
+    }
+
+    context.insert("assets", &assets);
 
     let rendered = tera.render(&page.template, &context).unwrap();
 
@@ -152,30 +321,272 @@ fn render(tera: &Tera, page: Page, triple: PageTriple) -> std::io::Result<()> {
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = std::env::args().collect();
-    let src_dir = args.get(1)
-        .map(String::clone)
-        .unwrap_or(String::from("src"));
+    let watch = args.iter().any(|arg| arg == "--watch");
+    let config_path = args.iter()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .cloned()
+        .unwrap_or(String::from("site.toml"));
 
-    let src_dir = Path::new(&src_dir);
-    let out_dir = Path::new("public");
+    let config = Config::load(Path::new(&config_path));
 
-    if !out_dir.is_dir() {
-        fs::create_dir(out_dir)?;
-    }
-    
-    let mut tera = Tera::new("templates/**/*.html").unwrap();
+    let src_dir = Path::new(&config.content_dir).to_owned();
+    let out_dir = Path::new(&config.output_dir).to_owned();
+    let templates_glob = format!("{}/**/*.html", config.templates_dir);
+
+    clean_out_dir(&out_dir)?;
+
+    let mut tera = Tera::new(&templates_glob).unwrap();
     tera.autoescape_on(vec![]);
 
+    build_all(&tera, &src_dir, &out_dir, &config)?;
+
+    if watch {
+        watch_and_rebuild(&mut tera, &templates_glob, &src_dir, &out_dir, &config)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes and recreates `out_dir` so renamed or deleted pages don't leave
+/// orphans behind from a previous build. Refuses to touch anything outside
+/// the project directory.
+fn clean_out_dir(out_dir: &Path) -> std::io::Result<()> {
+    if out_dir.is_absolute() || out_dir.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+        panic!("refusing to clean out_dir outside the project: {:?}", out_dir);
+    }
+
+    if out_dir.is_dir() {
+        fs::remove_dir_all(out_dir)?;
+    }
+    fs::create_dir_all(out_dir)?;
+    Ok(())
+}
+
+/// Renders every `.md` file under `src_dir` into `out_dir`, then generates
+/// the tag index pages from the tags each one carried in its front matter.
+fn build_all(tera: &Tera, src_dir: &Path, out_dir: &Path, config: &Config) -> std::io::Result<()> {
     let srcs = get_all_src(src_dir)?;
+    let mut summaries = Vec::with_capacity(srcs.len());
     for src in srcs {
-        let triple = PageTriple::new(src, src_dir, out_dir);
-        let page = Page::new(&triple);
-        render(&tera, page, triple)?;
+        summaries.push(build_one(tera, src, src_dir, out_dir, config)?);
+    }
+
+    build_tag_pages(tera, &summaries, out_dir, config)?;
+
+    Ok(())
+}
+
+/// Parses and renders a single source file, returning just enough about it
+/// (uri, title, tags) to fold into the tag index.
+fn build_one(tera: &Tera, src: PathBuf, src_dir: &Path, out_dir: &Path, config: &Config) -> std::io::Result<PageSummary> {
+    let triple = PageTriple::new(src, src_dir, out_dir);
+    let page = Page::new(&triple);
+    let triple = triple.with_slug(page.slug.as_deref(), out_dir);
+    let summary = PageSummary {
+        uri: triple.uri.to_string_lossy().into_owned(),
+        title: page.title.clone(),
+        tags: page.tags.clone(),
+    };
+    render(tera, page, triple, config)?;
+    Ok(summary)
+}
+
+/// Everything the taxonomy pass needs to know about a rendered page.
+struct PageSummary {
+    uri: String,
+    title: String,
+    tags: Vec<String>,
+}
+
+/// A page listed on a tag's index page.
+#[derive(Debug, Serialize)]
+struct TagPage {
+    uri: String,
+    title: String,
+}
+
+/// An entry on the top-level `/tags/` listing.
+#[derive(Debug, Serialize)]
+struct TagIndexEntry {
+    name: String,
+    uri: String,
+    count: usize,
+}
+
+/// Groups pages by the tags in their front matter and writes one
+/// `/tags/<slug>/index.html` per tag plus a top-level `/tags/` listing.
+fn build_tag_pages(tera: &Tera, summaries: &[PageSummary], out_dir: &Path, config: &Config) -> std::io::Result<()> {
+    let mut by_tag: std::collections::BTreeMap<String, Vec<TagPage>> = std::collections::BTreeMap::new();
+    for summary in summaries {
+        for tag in &summary.tags {
+            by_tag.entry(tag.clone()).or_default().push(TagPage {
+                uri: summary.uri.clone(),
+                title: summary.title.clone(),
+            });
+        }
     }
-   
+
+    for (tag, pages) in &by_tag {
+        let uri = format!("/tags/{}/", slug::slugify(tag));
+        let out = out_dir.join("tags").join(slug::slugify(tag)).join("index.html");
+        fs::create_dir_all(out.parent().unwrap())?;
+
+        let mut context = Context::new();
+        context.insert("config", config);
+        context.insert("page", &PageContext {
+            permalink: format!("{}{}", config.base_url.trim_end_matches('/'), uri),
+            uri,
+            date: None,
+            slug: None,
+        });
+        context.insert("tag", tag);
+        context.insert("pages", pages);
+
+        let rendered = tera.render(&config.tag_template, &context).unwrap();
+        fs::write(&out, rendered)?;
+    }
+
+    let tags: Vec<TagIndexEntry> = by_tag.iter()
+        .map(|(tag, pages)| TagIndexEntry {
+            name: tag.clone(),
+            uri: format!("/tags/{}/", slug::slugify(tag)),
+            count: pages.len(),
+        })
+        .collect();
+
+    let uri = String::from("/tags/");
+    let out = out_dir.join("tags").join("index.html");
+    fs::create_dir_all(out.parent().unwrap())?;
+
+    let mut context = Context::new();
+    context.insert("config", config);
+    context.insert("page", &PageContext {
+        permalink: format!("{}{}", config.base_url.trim_end_matches('/'), uri),
+        uri,
+        date: None,
+        slug: None,
+    });
+    context.insert("tags", &tags);
+
+    let rendered = tera.render(&config.tags_index_template, &context).unwrap();
+    fs::write(&out, rendered)?;
+
     Ok(())
 }
 
+/// Watches `src_dir` and the templates directory for changes, debounced, and
+/// re-runs just the affected part of the pipeline: a changed `.md` file is
+/// re-parsed and re-rendered on its own, while a changed template triggers a
+/// full `Tera` reload and rebuild.
+fn watch_and_rebuild(
+    tera: &mut Tera,
+    templates_glob: &str,
+    src_dir: &Path,
+    out_dir: &Path,
+    config: &Config,
+) -> std::io::Result<()> {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+    use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
+
+    println!("-- watching {:?} and {:?} for changes...", src_dir, config.templates_dir);
+
+    let cwd = std::env::current_dir()?;
+    let (tx, rx) = channel();
+    let mut debouncer = new_debouncer(Duration::from_millis(300), tx).unwrap();
+    debouncer.watcher().watch(src_dir, RecursiveMode::Recursive).unwrap();
+    debouncer.watcher().watch(Path::new(&config.templates_dir), RecursiveMode::Recursive).unwrap();
+
+    for result in rx {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("watch error: {:?}", e);
+                continue;
+            }
+        };
+
+        let mut templates_changed = false;
+        let mut pages_changed: Vec<PathBuf> = vec![];
+        for event in events {
+            // notify reports absolute paths; make them relative to the working
+            // directory like `templates_dir`/`src_dir` so they compare and strip
+            let path = event.path.strip_prefix(&cwd)
+                .map(Path::to_path_buf)
+                .unwrap_or(event.path);
+            if path.starts_with(&config.templates_dir) {
+                templates_changed = true;
+            } else if path.extension().map(OsStr::to_str).flatten() == Some("md") {
+                pages_changed.push(path);
+            }
+        }
+
+        if templates_changed {
+            println!("-- reloading templates...");
+            *tera = Tera::new(templates_glob).unwrap();
+            tera.autoescape_on(vec![]);
+            build_all(tera, src_dir, out_dir, config)?;
+        } else {
+            for src in pages_changed {
+                if src.is_file() {
+                    build_one(tera, src, src_dir, out_dir, config)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Copies every non-`.md` file next to `triple.src` into the page's output
+/// directory, so images/downloads co-located with a page ship alongside it.
+/// Only page bundles (`index.md`) own their directory this way; a
+/// non-index page's siblings may be other unrelated pages, so it copies
+/// nothing. Returns the copied filenames so templates can link to them.
+fn copy_page_assets(triple: &PageTriple) -> std::io::Result<Vec<String>> {
+    if !triple.is_index {
+        return Ok(vec![]);
+    }
+
+    let src_dir = triple.src.parent().unwrap();
+    let out_dir = triple.out.parent().unwrap();
+
+    let mut assets = vec![];
+    for entry in fs::read_dir(src_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if path.extension().map(OsStr::to_str).flatten() == Some("md") {
+            continue;
+        }
+
+        let file_name = path.file_name().unwrap();
+        fs::copy(&path, out_dir.join(file_name))?;
+        assets.push(file_name.to_string_lossy().into_owned());
+    }
+
+    Ok(assets)
+}
+
+/// Stamps each rendered `<h1>`-`<h6>` tag with the `id` of its corresponding
+/// `Toc` entry, in document order, so that `toc` links resolve to anchors.
+/// Matches headings positionally rather than parsing the HTML, so raw
+/// `<hN>` tags written by hand in the markdown body (allowed since
+/// `allow_dangerous_html` is on) will desync this from `toc`.
+fn inject_heading_ids(content_html: &str, toc: &[Toc]) -> String {
+    let heading_re = regex::Regex::new(r"(?s)<h([1-6])>(.*?)</h[1-6]>").unwrap();
+    let mut entries = toc.iter();
+
+    heading_re.replace_all(content_html, |caps: &regex::Captures| {
+        match entries.next() {
+            Some(toc) => format!("<h{0} id=\"{1}\">{2}</h{0}>", &caps[1], toc.id, &caps[2]),
+            None => caps[0].to_string(),
+        }
+    }).into_owned()
+}
 
 /// Returns the paths of all src files in a directory and all of its subdirectories
 fn get_all_src(src_dir: &Path) -> std::io::Result<Vec<PathBuf>> {